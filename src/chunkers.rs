@@ -1,6 +1,8 @@
-use crate::{rollsum::*, Sha256Sum};
+use crate::{
+    digest::{self, DigestKind},
+    rollsum::*,
+};
 use anyhow::{anyhow, ensure};
-use sha2::Digest;
 use std::{fmt, str::FromStr};
 use tracing::*;
 
@@ -8,7 +10,7 @@ pub struct Appearance {
     pub from: usize,
     pub len: usize,
     pub start_mark: u64,
-    pub hash: Sha256Sum,
+    pub hash: Vec<u8>,
 }
 
 impl fmt::Display for Appearance {
@@ -31,7 +33,7 @@ impl FromStr for Appearance {
         let from: usize = next_field()?.parse()?;
         let len: usize = next_field()?.parse()?;
         let start_mark = u64::from_str_radix(next_field()?, 16)?;
-        let hash = hex::decode(next_field()?)?.try_into().unwrap();
+        let hash = hex::decode(next_field()?)?;
         Ok(Appearance {
             from,
             len,
@@ -41,9 +43,22 @@ impl FromStr for Appearance {
     }
 }
 
+/// Turn an iterator of chunk appearances into breakpoints that bound each
+/// chunk on both ends, not just at its start. Format-aware chunkers like
+/// [`chunk_tarball`] and [`chunk_sparse`] can skip over bytes that don't
+/// belong to any appearance (padding, other chunk types, holes); pushing
+/// only `ap.from` as a breakpoint would let the final reconstruction in
+/// `main.rs::chunk()` fold those skipped bytes into the preceding chunk.
+pub fn appearance_breakpoints(
+    appearances: impl Iterator<Item = Appearance>,
+) -> impl Iterator<Item = usize> {
+    appearances.flat_map(|ap| [ap.from, ap.from + ap.len])
+}
+
 pub fn chunk_uniform(
     file: &[u8],
     size: usize,
+    kind: DigestKind,
 ) -> anyhow::Result<impl Iterator<Item = Appearance> + '_> {
     ensure!(size >= WINDOW_SIZE, "Chunk size too small");
     info!(
@@ -59,7 +74,7 @@ pub fn chunk_uniform(
             if from % size == 0 {
                 let start_mark = hasher.sum();
                 let to = file.len().min(from + size);
-                let hash = sha2::Sha256::digest(&file[from..to]).try_into().unwrap();
+                let hash = digest::digest(kind, &file[from..to]);
                 return Some(Appearance {
                     from,
                     len: size,
@@ -72,7 +87,7 @@ pub fn chunk_uniform(
     }))
 }
 
-pub fn chunk_specific(file: &[u8], mut from: usize, mut len: usize) -> Appearance {
+pub fn chunk_specific(file: &[u8], mut from: usize, mut len: usize, kind: DigestKind) -> Appearance {
     if from + WINDOW_SIZE >= file.len() {
         from = file.len() - WINDOW_SIZE;
         len = WINDOW_SIZE;
@@ -96,9 +111,7 @@ pub fn chunk_specific(file: &[u8], mut from: usize, mut len: usize) -> Appearanc
         hasher.input(x);
     }
     let start_mark = hasher.sum();
-    let hash = sha2::Sha256::digest(&file[from..from + len])
-        .try_into()
-        .unwrap();
+    let hash = digest::digest(kind, &file[from..from + len]);
     Appearance {
         from,
         len,
@@ -107,7 +120,185 @@ pub fn chunk_specific(file: &[u8], mut from: usize, mut len: usize) -> Appearanc
     }
 }
 
-pub fn chunk_tarball(file: &[u8]) -> impl Iterator<Item = Appearance> + '_ {
+/// A table of fixed pseudo-random values used by [`chunk_fastcdc`] to turn
+/// window contents into a rolling fingerprint. The values themselves don't
+/// matter, only that they're fixed, so results are reproducible across runs.
+#[rustfmt::skip]
+const GEAR: [u64; 256] = [
+    0x950e87d7f5606615, 0x2c61275c9e6b6cf8, 0x1f00bca0042db923, 0x6dbca290a9eab706,
+    0x4c10a4fe30cffdda, 0xf26fff4cc4fd394d, 0x6814a2bc786a6d2d, 0xa26b351e6c8042c5,
+    0x54760e7fbc051c6c, 0xd4c08880a5a4666d, 0x29610ae0eed8f1e7, 0xc34bd8e2fe5213e5,
+    0x6c50afb6e9fb123d, 0x6f28d015a2aa0b9d, 0x4e385994ebac94af, 0x194f9545adba52ce,
+    0xc675ce05588f882f, 0x57de8c051d4b7ef2, 0xd998efd82733e933, 0x6df216c33f8f3201,
+    0x11dc6f3fcb57d5d8, 0x8860a84722025e05, 0x33176469aa6ef630, 0x607507ebc5b864d7,
+    0x7a2f11088d29b146, 0xda10faaa6fc24b83, 0x2de288f12fcb9940, 0xb98937dfef041066,
+    0xdd4b712ed355871e, 0xc5b790314a2e3224, 0x07fdc889fa017ed7, 0x81eeadd71198bf15,
+    0x3a46305c425a7de1, 0xaaabc8d366e0440d, 0x3371364fc51d1a5e, 0x4763dd191ac44b70,
+    0x016590c55646e6d0, 0x0b7a6e1d81e4b9e7, 0xe5a2a8bef16e981a, 0x1167fba4a2927979,
+    0x3d01ac0f1b534b87, 0xd27a5f0f5532c867, 0xee26cbc0358b24d3, 0x9bdb39b2ca3c6a00,
+    0x8de06fbe1a741555, 0xd6257b492186c8b5, 0xdee7539c539445f3, 0x4307513f1ec1b0b1,
+    0x1d790bcaeffd4d2d, 0xde18f50a43cf423a, 0xd36c78ab3537a844, 0x64b5e3f81a293b3b,
+    0xe8eef3d67646f8a9, 0xa88d379db047719d, 0xf177d49f03ddc3bf, 0xa745fdd552965bca,
+    0xd0b6a46a7048daca, 0xfce79398852e0400, 0x760c9b756320dbe3, 0x4e52b41980271e94,
+    0x293f65848aa18f43, 0x520e015e444ed0f2, 0x793ff51bb0baf029, 0x7ad955568f86a26a,
+    0x1c720603ec8602d9, 0xd08e7565d487d342, 0x310288290b43dbfb, 0xd50ca99e8e59ea07,
+    0x6c24e82c6dbbac73, 0xb7a13dce8e4595df, 0xe91b8ec1f011e633, 0x9293bf4aed9a76b9,
+    0x75c33f8fcb8031fe, 0x1e7c31d385989296, 0x5574e314ddfc20fe, 0xd17dad339930e76e,
+    0xacfbba2a3f8666ee, 0xa4e307830deef007, 0x8fcd110ce94f47b0, 0xe1660a4195d74835,
+    0xd6d91d39227d512d, 0x2abb018969cbe6eb, 0x09cea2a86a921843, 0x3fe9e76493a8b5d8,
+    0x602f8e87d16bc8be, 0xe376bd78d7304cb6, 0x748781c961ef7dfc, 0xff5e243c496a590b,
+    0x089934a93d71d058, 0x3deadc7d1d2e1a2e, 0xe443e6031233f1e0, 0x5ab59d10b4a20569,
+    0x658141e73ede6f12, 0xf5d46d8127762b7b, 0xad1dd1408b87cfcb, 0xf9afa64760083c7d,
+    0xb7a68aa8611b9b59, 0xd828056ea86fc09c, 0x1c0ae9a87893032b, 0x34c8a05ca34be96a,
+    0xc966aed65a10eeaf, 0x6b7e21f0921082df, 0x6e5d9a3007c331a3, 0x3a0806a754f57983,
+    0x0a07a198f7767fd6, 0xf0723a8383f43dc4, 0xfb65e62582414d3f, 0x504516f2106025b5,
+    0xa0d72f15feb859eb, 0x115600523ea6fb4d, 0x1be3ae0c3b97b6c9, 0x5fe2b11364b97756,
+    0x5a8a944097dea5e8, 0xc330642bbf1317f8, 0xf0b02956ff594f79, 0xa4002d902b1b1e58,
+    0xba351d1d2912ab9f, 0x56761e8879073c59, 0x3912a0fca373e01b, 0xec004af1d0efd4ff,
+    0x8919551203d33d87, 0x64f85da91a44dfa0, 0x21d287d8efb4cad1, 0x1732b75d08d75496,
+    0x27623245c6251a5c, 0x987abb69ec5093da, 0xea45cdaf628e21c8, 0x0272834f4d8a9084,
+    0xab699ad2c231185b, 0x6ff327f4119ee914, 0x6b06b34098ca4c3f, 0x725461191d5d7302,
+    0x511173b251af8015, 0xebbfbb2bc3846ece, 0xed8b79ed1d74a080, 0x9736b29f0b03d0e1,
+    0xceaf0df42de3540c, 0x576c473aecbeb26f, 0x6782e42f80a0f27d, 0xf39f015e2cafb91c,
+    0x293c27e425e74da2, 0x1a18b9b1c2c8b502, 0x731535ecb7b2a53b, 0x4f7d9b08c0f76e59,
+    0x3e115e3e75118be1, 0x689db40cdd801db4, 0x399246294d8fc042, 0xc018ee73ff8f5cff,
+    0xa364f1b057f4865e, 0xbd5993b1f9f2dce0, 0x1fb37062a68f65c1, 0x2a5f2d8aca707a92,
+    0x3ff1295c1d296c14, 0x4ea7feaa1455fcad, 0xb484b8d3f354db28, 0xdef5e3507a2ee034,
+    0x1a46b9e3a2663f03, 0x5665aca3177d70d6, 0x36a208e01b1b4ee3, 0x00822ed4e33a0336,
+    0x9d3bd30e22749e54, 0x703666d165265fe5, 0xebe4418c6286ef71, 0xe07f915527fcb0f2,
+    0xcfedc87950868c9c, 0x95825097784ecbbb, 0x106572c92038d12e, 0x79b713272176822e,
+    0x810287a90cffae31, 0x7c8f5a44b03c1008, 0x113167635255aa79, 0x9f0600356aab79e5,
+    0x559ccfb8c80ce420, 0x33fc57dd263695f9, 0xc2299345df0b305d, 0x3519cb88dac97abb,
+    0xed1137eb3e5e1046, 0x22b6ce988e5e8733, 0xe3bd76bf57cec991, 0x402117a53e2681d1,
+    0xeee4852d330c2394, 0x854773512f3334bf, 0xcfe680854c95ea72, 0xe3aab3ddc209f79d,
+    0xa2842cb2fb44c6a2, 0x32442b01a0f4dd5a, 0xe5fbc6d02bd667d6, 0x343c5382621d123a,
+    0x6cb5b7d2782a1890, 0xef04a4a598411feb, 0x31afaa01fdc2dbd7, 0x5762032f27aa949b,
+    0x332508b2d1c97795, 0xb93ad7dfcba7ddcd, 0x4930986a215c9b8b, 0x3caf648a3fe36a17,
+    0x4e1309a0fc447a7f, 0x019d6ac5fe7f773e, 0x637118bb0b0e773c, 0xba17e7bd0a7a8b0c,
+    0x20b9122fca694c79, 0xb0773e1b8ea50117, 0xa544b6d2cf823377, 0x3e2e21041529057c,
+    0x01d6aedaa22e88e8, 0x673bb9153bc7eead, 0xf332dec5058c062b, 0x802df2eef9537531,
+    0x26dd7c451562a836, 0x0c72e5f1f03cde37, 0xeae27c2bcf28335a, 0x9482faca03ac665d,
+    0x6774a90031d2ba09, 0xe6b37c203fbd6d30, 0xc958935b157304b1, 0x9ef80467a8e636c6,
+    0xa7d73426f0aee715, 0x4ac05557bdca343f, 0x65c2195389de9f30, 0x7b4afcc0a8108c27,
+    0x938f35b2dc04bbfc, 0x642e484600cdfa67, 0x890c62927989d7e6, 0x11d0bc174b47a18b,
+    0xd0ae2b468f227e2f, 0xb9f409d40d3832c1, 0xa37579c44c86abf9, 0xcc69f35beecff786,
+    0x3cd64d14ac521437, 0xb860c5a45b4be237, 0x3d1791cf2b9550bc, 0x4c5b4726a89a476e,
+    0x12e2992b24380fb6, 0x0fb88164ccc14927, 0x9dca0bdcdd3a68c5, 0xeb0e37f4d6290f03,
+    0x0e8936d8133fee34, 0x2e778e78671eaa35, 0x616eb2a9fb09b28d, 0xaac0c22e5d235cab,
+    0xad4cf62c94a4f317, 0xcf3b5ee99ca944bb, 0xc1f007cd2413872a, 0x18fde7a7091e9247,
+    0xe8ed59599a0e9c30, 0xb036bade9e716b3d, 0x92852160c8b912b1, 0x59ad98498ff5b11b,
+    0xd41339c948a6e7cb, 0x3c79a0009f140b4e, 0x34186cdd3c3c5140, 0x919b6a673343fd70,
+    0xbab5120ef942a0f6, 0x3c8016d006c1ec71, 0x28e208906796f59f, 0xfbd9efbb76c9773a,
+];
+
+/// How many bits narrower/wider than `bits` the "hard" and "easy" masks are.
+/// Keeps the size distribution tight around `avg` (see `fastcdc_masks`).
+const NORMALIZATION: u32 = 2;
+
+/// Derive the FastCDC "hard" and "easy" masks for a target average chunk
+/// size. Both masks have their one-bits packed into the high end of the
+/// word, matching the gear hash's `fp = (fp << 1) + GEAR[b]` update, which
+/// biases entropy towards the high bits too.
+fn fastcdc_masks(avg: usize) -> (u64, u64) {
+    let bits = (avg as f64).log2().round() as u32;
+    let mask_s = !0u64 << (64 - (bits + NORMALIZATION));
+    let mask_l = !0u64 << (64 - bits.saturating_sub(NORMALIZATION));
+    (mask_s, mask_l)
+}
+
+/// Find the next FastCDC cut point at or after `from`, using cut-point
+/// skipping: the first `min` bytes aren't hashed at all.
+fn fastcdc_next_cut(file: &[u8], from: usize, min: usize, avg: usize, max: usize) -> usize {
+    if file.len() - from <= min {
+        return file.len();
+    }
+    let (mask_s, mask_l) = fastcdc_masks(avg);
+    let mut fp: u64 = 0;
+    let avg_end = file.len().min(from + avg);
+    for i in (from + min)..avg_end {
+        fp = (fp << 1).wrapping_add(GEAR[file[i] as usize]);
+        if fp & mask_s == 0 {
+            return i + 1;
+        }
+    }
+    let max_end = file.len().min(from + max);
+    for i in avg_end..max_end {
+        fp = (fp << 1).wrapping_add(GEAR[file[i] as usize]);
+        if fp & mask_l == 0 {
+            return i + 1;
+        }
+    }
+    max_end
+}
+
+/// Content-defined chunking a la FastCDC. Unlike [`chunk_uniform`], cut
+/// points are derived from a rolling fingerprint of the data itself, so
+/// they survive insertions/deletions upstream instead of shifting with
+/// every byte offset downstream of an edit.
+///
+/// Unlike [`chunk_tarball`] or [`chunk_sparse`], this returns raw breakpoint
+/// offsets rather than an `Appearance` iterator, since there's no format
+/// structure here telling us where a chunk "should" start — the caller's
+/// `Vec<usize>` of breakpoints can `extend` from this directly.
+pub fn chunk_fastcdc(file: &[u8], min: usize, avg: usize, max: usize) -> Vec<usize> {
+    info!(
+        "Chunking a {} MiB file with FastCDC (min {} KiB, avg {} KiB, max {} KiB)",
+        file.len() / (1024 * 1024),
+        min / 1024,
+        avg / 1024,
+        max / 1024,
+    );
+    let mut breakpoints = Vec::new();
+    let mut from = 0;
+    while from < file.len() {
+        let to = fastcdc_next_cut(file, from, min, avg, max);
+        breakpoints.push(to);
+        from = to;
+    }
+    breakpoints
+}
+
+/// Find the next Asymmetric Extremum cut point at or after `from`: the
+/// point `w` bytes past the running maximum byte value, i.e. the first byte
+/// that isn't beaten by anything in the `w` bytes after it.
+fn ae_next_cut(file: &[u8], from: usize, w: usize) -> usize {
+    if file.len() <= from + 1 {
+        return file.len();
+    }
+    let mut max_val = file[from];
+    let mut max_pos = from;
+    for (p, &byte) in file.iter().enumerate().skip(from + 1) {
+        if byte > max_val {
+            max_val = byte;
+            max_pos = p;
+        } else if p == max_pos + w {
+            return p;
+        }
+    }
+    file.len()
+}
+
+/// Content-defined chunking via the Asymmetric Extremum algorithm. Unlike
+/// [`chunk_fastcdc`], this needs no rolling hash: it's a single pass that
+/// just tracks the local maximum byte, so it runs at memory-bandwidth speed
+/// rather than being bottlenecked by a hash per byte.
+pub fn chunk_ae(file: &[u8], w: usize) -> Vec<usize> {
+    info!(
+        "Chunking a {} MiB file with AE (window {} KiB)",
+        file.len() / (1024 * 1024),
+        w / 1024,
+    );
+    let mut breakpoints = Vec::new();
+    let mut from = 0;
+    while from < file.len() {
+        let to = ae_next_cut(file, from, w);
+        breakpoints.push(to);
+        from = to;
+    }
+    breakpoints
+}
+
+pub fn chunk_tarball(file: &[u8], kind: DigestKind) -> impl Iterator<Item = Appearance> + '_ {
     let mut offset = 0;
     info!("file len: {}", file.len());
     std::iter::from_fn(move || {
@@ -121,8 +312,258 @@ pub fn chunk_tarball(file: &[u8]) -> impl Iterator<Item = Appearance> + '_ {
         let _g = info_span!("", %filename).entered();
         let x = ((data_len - 1) / 512) + 1; // round to 512 bytes
         let entry_len = (x + 1) * 512; // add 512 for the header
-        let chunk = chunk_specific(file, offset, entry_len);
+        let chunk = chunk_specific(file, offset, entry_len, kind);
         offset += entry_len;
         Some(chunk)
     })
 }
+
+const SPARSE_MAGIC: u32 = 0xED26FF3A;
+const SPARSE_CHUNK_RAW: u16 = 0xCAC1;
+// Other chunk types (fill 0xCAC2, don't-care 0xCAC3, crc32 0xCAC4) carry no
+// seed-reusable data, so they never become chunks of their own.
+
+fn read_u16(file: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes(file[at..at + 2].try_into().unwrap())
+}
+
+fn read_u32(file: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(file[at..at + 4].try_into().unwrap())
+}
+
+/// Chunk an Android sparse image (as produced by `img2simg`), placing
+/// breakpoints at the data boundaries of its "raw" chunks. Fill, don't-care,
+/// and crc32 chunks are skipped entirely: don't-care chunks are holes with
+/// no data to reuse, and the others are too small to be worth chunking.
+pub fn chunk_sparse(
+    file: &[u8],
+    kind: DigestKind,
+) -> anyhow::Result<impl Iterator<Item = Appearance> + '_> {
+    ensure!(file.len() >= 28, "File too small to be a sparse image");
+    ensure!(
+        read_u32(file, 0) == SPARSE_MAGIC,
+        "Not an Android sparse image (bad magic)"
+    );
+    let file_hdr_sz = read_u16(file, 8) as usize;
+    let chunk_hdr_sz = read_u16(file, 10) as usize;
+    let blk_sz = read_u32(file, 12) as usize;
+    let total_chunks = read_u32(file, 20) as usize;
+    info!(
+        "Android sparse image: {total_chunks} chunks, {}-byte blocks",
+        blk_sz
+    );
+    ensure!(chunk_hdr_sz > 0, "Chunk header size must be non-zero");
+    ensure!(
+        file_hdr_sz <= file.len(),
+        "File header size overruns the file"
+    );
+    // A crafted image can claim an arbitrarily large total_chunks; cap the
+    // capacity hint at what the file could actually hold so we don't abort
+    // on allocation before the per-chunk bounds checks below ever run.
+    let max_possible_chunks = (file.len() - file_hdr_sz) / chunk_hdr_sz;
+
+    // Validate every chunk header up front, so a truncated or corrupt image
+    // is reported as an error instead of panicking partway through iteration.
+    let mut offset = file_hdr_sz;
+    let mut chunks = Vec::with_capacity(total_chunks.min(max_possible_chunks));
+    for _ in 0..total_chunks {
+        ensure!(
+            offset + chunk_hdr_sz <= file.len(),
+            "Truncated sparse image: chunk header at {offset} overruns the file"
+        );
+        let chunk_type = read_u16(file, offset);
+        let chunk_sz = read_u32(file, offset + 4) as usize;
+        let total_sz = read_u32(file, offset + 8) as usize;
+        ensure!(
+            total_sz >= chunk_hdr_sz,
+            "Chunk at {offset} has total_sz smaller than the chunk header"
+        );
+        let next_offset = offset
+            .checked_add(total_sz)
+            .filter(|&n| n <= file.len())
+            .ok_or_else(|| anyhow!("Chunk at {offset} (total_sz {total_sz}) overruns the file"))?;
+        let data_from = offset + chunk_hdr_sz;
+        if chunk_type == SPARSE_CHUNK_RAW {
+            let data_len = chunk_sz * blk_sz;
+            ensure!(
+                data_from + data_len <= file.len(),
+                "Raw chunk at {offset} (data_len {data_len}) overruns the file"
+            );
+            chunks.push(chunk_specific(file, data_from, data_len, kind));
+        }
+        offset = next_offset;
+    }
+    Ok(chunks.into_iter())
+}
+
+/// Given a sorted set of chunk boundaries, insert extra breakpoints so that
+/// no gap between consecutive entries exceeds `max_size`.
+pub fn split_large_chunks(breakpoints: &mut Vec<usize>, max_size: usize) {
+    breakpoints.sort();
+    breakpoints.dedup();
+    let mut extra = Vec::new();
+    for (&from, &to) in breakpoints.iter().zip(breakpoints.iter().skip(1)) {
+        let mut pos = from + max_size;
+        while pos < to {
+            extra.push(pos);
+            pos += max_size;
+        }
+    }
+    breakpoints.extend(extra);
+    breakpoints.sort();
+    breakpoints.dedup();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A small deterministic PRNG so tests don't depend on an external crate.
+    fn pseudo_random_bytes(len: usize, mut seed: u64) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                (seed & 0xff) as u8
+            })
+            .collect()
+    }
+
+    fn assert_breakpoints_cover_file(breakpoints: &[usize], file_len: usize) {
+        assert!(!breakpoints.is_empty());
+        assert!(breakpoints[0] > 0, "first breakpoint should not be 0");
+        assert_eq!(*breakpoints.last().unwrap(), file_len);
+        for w in breakpoints.windows(2) {
+            assert!(w[0] < w[1], "breakpoints must be strictly increasing");
+        }
+    }
+
+    #[test]
+    fn fastcdc_breakpoints_cover_file_and_respect_bounds() {
+        let file = pseudo_random_bytes(256 * 1024, 0xC0FFEE);
+        let (min, avg, max) = (2 * 1024, 8 * 1024, 64 * 1024);
+        let breakpoints = chunk_fastcdc(&file, min, avg, max);
+        assert_breakpoints_cover_file(&breakpoints, file.len());
+
+        let mut from = 0;
+        let n = breakpoints.len();
+        for (i, &to) in breakpoints.iter().enumerate() {
+            let len = to - from;
+            assert!(len <= max, "chunk of {len} bytes exceeds max {max}");
+            // Only the final chunk is allowed to be shorter than `min`: it's
+            // whatever's left over once there's no room for another cut.
+            if i + 1 != n {
+                assert!(len >= min, "chunk of {len} bytes is under min {min}");
+            }
+            from = to;
+        }
+    }
+
+    #[test]
+    fn ae_breakpoints_cover_file() {
+        let file = pseudo_random_bytes(256 * 1024, 0xDEADBEEF);
+        let breakpoints = chunk_ae(&file, 8 * 1024);
+        assert_breakpoints_cover_file(&breakpoints, file.len());
+    }
+
+    /// Builds a minimal Android sparse image: one raw chunk holding `raw_data`,
+    /// one fill chunk, one don't-care (hole) chunk, and one crc32 chunk.
+    fn mk_sparse_image(blk_sz: u32, raw_data: &[u8]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let file_hdr_sz: u16 = 28;
+        let chunk_hdr_sz: u16 = 12;
+        buf.extend(SPARSE_MAGIC.to_le_bytes());
+        buf.extend(1u16.to_le_bytes()); // major_version
+        buf.extend(0u16.to_le_bytes()); // minor_version
+        buf.extend(file_hdr_sz.to_le_bytes());
+        buf.extend(chunk_hdr_sz.to_le_bytes());
+        buf.extend(blk_sz.to_le_bytes());
+        let total_blks = raw_data.len() as u32 / blk_sz + 1 + 1;
+        buf.extend(total_blks.to_le_bytes());
+        buf.extend(4u32.to_le_bytes()); // total_chunks
+        buf.extend(0u32.to_le_bytes()); // image checksum
+        assert_eq!(buf.len(), file_hdr_sz as usize);
+
+        // Raw chunk: header + data.
+        buf.extend(0xCAC1u16.to_le_bytes());
+        buf.extend(0u16.to_le_bytes()); // reserved
+        buf.extend((raw_data.len() as u32 / blk_sz).to_le_bytes()); // chunk_sz
+        buf.extend((chunk_hdr_sz as u32 + raw_data.len() as u32).to_le_bytes()); // total_sz
+        buf.extend_from_slice(raw_data);
+
+        // Fill chunk: header + 4-byte fill value.
+        buf.extend(0xCAC2u16.to_le_bytes());
+        buf.extend(0u16.to_le_bytes());
+        buf.extend(1u32.to_le_bytes()); // chunk_sz
+        buf.extend((chunk_hdr_sz as u32 + 4).to_le_bytes()); // total_sz
+        buf.extend(0u32.to_le_bytes()); // fill value
+
+        // Don't-care chunk: header only, no data.
+        buf.extend(0xCAC3u16.to_le_bytes());
+        buf.extend(0u16.to_le_bytes());
+        buf.extend(1u32.to_le_bytes()); // chunk_sz
+        buf.extend((chunk_hdr_sz as u32).to_le_bytes()); // total_sz
+
+        // Crc32 chunk: header + 4-byte crc.
+        buf.extend(0xCAC4u16.to_le_bytes());
+        buf.extend(0u16.to_le_bytes());
+        buf.extend(0u32.to_le_bytes()); // chunk_sz
+        buf.extend((chunk_hdr_sz as u32 + 4).to_le_bytes()); // total_sz
+        buf.extend(0u32.to_le_bytes()); // crc
+
+        buf
+    }
+
+    #[test]
+    fn chunk_sparse_skips_everything_but_raw_chunks() {
+        let raw_data = pseudo_random_bytes(WINDOW_SIZE, 1);
+        let image = mk_sparse_image(WINDOW_SIZE as u32, &raw_data);
+
+        let appearances: Vec<_> = chunk_sparse(&image, DigestKind::Sha256).unwrap().collect();
+        assert_eq!(appearances.len(), 1);
+        // 28-byte file header, then a 12-byte chunk header, before the raw data.
+        assert_eq!(appearances[0].from, 40);
+        assert_eq!(appearances[0].len, raw_data.len());
+    }
+
+    #[test]
+    fn chunk_sparse_breakpoints_bound_raw_chunks_on_both_ends() {
+        // The fill/don't-care/crc32 chunks mk_sparse_image writes after the
+        // raw chunk aren't appearances, so `main.rs::chunk()` only learns
+        // about them via the end-of-raw-chunk breakpoint. Without it, the
+        // reconstruction loop would fold those non-raw bytes into the raw
+        // chunk's recorded length and digest.
+        let raw_data = pseudo_random_bytes(WINDOW_SIZE, 1);
+        let image = mk_sparse_image(WINDOW_SIZE as u32, &raw_data);
+
+        let appearances = chunk_sparse(&image, DigestKind::Sha256).unwrap();
+        let breakpoints: Vec<usize> = appearance_breakpoints(appearances).collect();
+        assert_eq!(breakpoints, vec![40, 40 + raw_data.len()]);
+    }
+
+    #[test]
+    fn chunk_sparse_rejects_absurd_total_chunks_without_huge_allocation() {
+        let raw_data = pseudo_random_bytes(WINDOW_SIZE, 1);
+        let mut image = mk_sparse_image(WINDOW_SIZE as u32, &raw_data);
+        // Claim far more chunks than the file could possibly hold.
+        image[20..24].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(chunk_sparse(&image, DigestKind::Sha256).is_err());
+    }
+
+    #[test]
+    fn chunk_sparse_rejects_truncated_image() {
+        let raw_data = pseudo_random_bytes(WINDOW_SIZE, 1);
+        let image = mk_sparse_image(WINDOW_SIZE as u32, &raw_data);
+        // Cut the file off partway through the first chunk header.
+        let truncated = &image[..28 + 5];
+        assert!(chunk_sparse(truncated, DigestKind::Sha256).is_err());
+    }
+
+    #[test]
+    fn chunk_sparse_rejects_bad_magic() {
+        let mut image = mk_sparse_image(WINDOW_SIZE as u32, &pseudo_random_bytes(WINDOW_SIZE, 1));
+        image[0] = 0;
+        assert!(chunk_sparse(&image, DigestKind::Sha256).is_err());
+    }
+}
@@ -1,6 +1,8 @@
-use crate::rollsum::*;
-use anyhow::anyhow;
-use sha2::Digest;
+use crate::{
+    digest::{self, DigestKind},
+    rollsum::*,
+};
+use anyhow::{anyhow, ensure};
 use std::{
     collections::HashMap,
     fs::File,
@@ -10,21 +12,21 @@ use std::{
 use std::{fmt, str::FromStr};
 use tracing::*;
 
-pub type Sha256Sum = [u8; 32];
-
 pub struct ControlFile {
     pub total_len: usize,
-    pub total_sha256: Sha256Sum,
-    pub chunks: HashMap<u64, Vec<(usize, Sha256Sum)>>,
-    pub appearances: HashMap<Sha256Sum, (usize, Vec<usize>)>,
+    pub digest_kind: DigestKind,
+    pub total_digest: Vec<u8>,
+    pub chunks: HashMap<u64, Vec<(usize, Vec<u8>)>>,
+    pub appearances: HashMap<Vec<u8>, (usize, Vec<usize>)>,
 }
 
 impl ControlFile {
     pub fn read(path: &Path) -> anyhow::Result<ControlFile> {
-        let mut chunks: HashMap<u64, Vec<(usize, Sha256Sum)>> = HashMap::default();
-        let mut appearances: HashMap<Sha256Sum, (usize, Vec<usize>)> = HashMap::default();
+        let mut chunks: HashMap<u64, Vec<(usize, Vec<u8>)>> = HashMap::default();
+        let mut appearances: HashMap<Vec<u8>, (usize, Vec<usize>)> = HashMap::default();
         let mut total_len = None;
-        let mut total_sha256 = None;
+        let mut total_digest = None;
+        let mut digest_kind = None;
 
         let config = BufReader::new(File::open(path)?);
         let mut lines = config
@@ -43,15 +45,25 @@ impl ControlFile {
                     let v = v.trim();
                     match k {
                         "Length" => total_len = Some(v.parse()?),
-                        "SHA-256" => total_sha256 = Some(hex::decode(v)?.try_into().unwrap()),
+                        "Hash" => digest_kind = Some(v.parse()?),
+                        // "SHA-256" is the old header name, kept for control
+                        // files written before the `Hash:` header existed.
+                        "SHA-256" | "Digest" => total_digest = Some(hex::decode(v)?),
                         _ => warn!("{k}: Unrecognised header"),
                     }
                 }
             }
         }
+        let digest_kind = digest_kind.unwrap_or(DigestKind::Sha256);
         for l in lines {
             let ap: Appearance = l.parse()?;
-            let appearances_entry = appearances.entry(ap.hash);
+            ensure!(
+                ap.hash.len() == digest_kind.byte_len(),
+                "Chunk digest is {} bytes, expected {} for {digest_kind}",
+                ap.hash.len(),
+                digest_kind.byte_len(),
+            );
+            let appearances_entry = appearances.entry(ap.hash.clone());
             if matches!(
                 appearances_entry,
                 std::collections::hash_map::Entry::Vacant(_)
@@ -59,7 +71,7 @@ impl ControlFile {
                 chunks
                     .entry(ap.start_mark)
                     .or_default()
-                    .push((ap.len, ap.hash));
+                    .push((ap.len, ap.hash.clone()));
             }
             appearances_entry
                 .or_insert_with(|| (ap.len, vec![]))
@@ -68,7 +80,8 @@ impl ControlFile {
         }
         Ok(ControlFile {
             total_len: total_len.ok_or_else(|| anyhow!("Missing key: Length"))?,
-            total_sha256: total_sha256.ok_or_else(|| anyhow!("Missing key: SHA-256"))?,
+            digest_kind,
+            total_digest: total_digest.ok_or_else(|| anyhow!("Missing key: Digest"))?,
             chunks,
             appearances,
         })
@@ -92,7 +105,7 @@ pub struct Appearance {
     pub from: usize,
     pub len: usize,
     pub start_mark: u64,
-    pub hash: Sha256Sum,
+    pub hash: Vec<u8>,
 }
 
 impl fmt::Display for Appearance {
@@ -115,7 +128,7 @@ impl FromStr for Appearance {
         let from: usize = next_field()?.parse()?;
         let len: usize = next_field()?.parse()?;
         let start_mark = u64::from_str_radix(next_field()?, 16)?;
-        let hash = hex::decode(next_field()?)?.try_into().unwrap();
+        let hash = hex::decode(next_field()?)?;
         Ok(Appearance {
             from,
             len,
@@ -126,7 +139,7 @@ impl FromStr for Appearance {
 }
 
 impl Appearance {
-    pub fn new(file: &[u8], mut from: usize, mut len: usize) -> Appearance {
+    pub fn new(file: &[u8], mut from: usize, mut len: usize, kind: DigestKind) -> Appearance {
         if from + WINDOW_SIZE >= file.len() {
             from = file.len() - WINDOW_SIZE;
             len = WINDOW_SIZE;
@@ -150,9 +163,7 @@ impl Appearance {
             roll_sum.input(x);
         }
         let start_mark = roll_sum.sum();
-        let hash = sha2::Sha256::digest(&file[from..from + len])
-            .try_into()
-            .unwrap();
+        let hash = digest::digest(kind, &file[from..from + len]);
         Appearance {
             from,
             len,
@@ -161,3 +172,60 @@ impl Appearance {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_control_file(name: &str, contents: &str) -> anyhow::Result<ControlFile> {
+        let path = std::env::temp_dir().join(format!("psync_test_{name}.psync"));
+        std::fs::write(&path, contents)?;
+        let result = ControlFile::read(&path);
+        std::fs::remove_file(&path)?;
+        result
+    }
+
+    #[test]
+    fn reads_current_hash_header() {
+        let digest = digest::digest(DigestKind::Xxh3_64, b"whole file contents");
+        let control_file = read_control_file(
+            "current_hash_header",
+            &format!(
+                "Length: 123\nHash: xxh3-64\nDigest: {}\n---\n",
+                hex::encode(&digest)
+            ),
+        )
+        .unwrap();
+        assert_eq!(control_file.total_len, 123);
+        assert_eq!(control_file.digest_kind, DigestKind::Xxh3_64);
+        assert_eq!(control_file.total_digest, digest);
+    }
+
+    #[test]
+    fn reads_legacy_sha256_header_and_defaults_digest_kind() {
+        // Control files written before the `Hash:` header existed have no
+        // way to name their digest algorithm, so they're assumed SHA-256.
+        let digest = digest::digest(DigestKind::Sha256, b"whole file contents");
+        let control_file = read_control_file(
+            "legacy_sha256_header",
+            &format!("Length: 123\nSHA-256: {}\n---\n", hex::encode(&digest)),
+        )
+        .unwrap();
+        assert_eq!(control_file.total_len, 123);
+        assert_eq!(control_file.digest_kind, DigestKind::Sha256);
+        assert_eq!(control_file.total_digest, digest);
+    }
+
+    #[test]
+    fn rejects_chunk_digest_of_the_wrong_width_for_the_declared_hash() {
+        let total_digest = digest::digest(DigestKind::Xxh3_64, b"whole file contents");
+        // A sha-256-sized (32-byte) chunk digest under a `Hash: xxh3-64`
+        // header should be rejected rather than silently accepted.
+        let bad_chunk_hash = hex::encode(digest::digest(DigestKind::Sha256, b"chunk"));
+        let contents = format!(
+            "Length: 123\nHash: xxh3-64\nDigest: {}\n---\n0\t5\t0\t{bad_chunk_hash}\n",
+            hex::encode(&total_digest)
+        );
+        assert!(read_control_file("bad_chunk_width", &contents).is_err());
+    }
+}
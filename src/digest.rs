@@ -0,0 +1,62 @@
+use anyhow::anyhow;
+use sha2::Digest as _;
+use std::{fmt, str::FromStr};
+
+/// Which hash function chunk digests (and the whole-file digest) are
+/// computed with. Recorded in the control file's `Hash:` header so `search`
+/// knows how to verify candidate chunks.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DigestKind {
+    /// Cryptographic, collision-resistant, and slow. The default.
+    Sha256,
+    /// Non-cryptographic and fast; fine once a rolling-hash match has
+    /// already narrowed things down to a handful of candidate chunks.
+    Xxh3_64,
+    Xxh3_128,
+}
+
+impl DigestKind {
+    /// Width of a digest of this kind, in bytes.
+    pub fn byte_len(self) -> usize {
+        match self {
+            DigestKind::Sha256 => 32,
+            DigestKind::Xxh3_64 => 8,
+            DigestKind::Xxh3_128 => 16,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            DigestKind::Sha256 => "sha-256",
+            DigestKind::Xxh3_64 => "xxh3-64",
+            DigestKind::Xxh3_128 => "xxh3-128",
+        }
+    }
+}
+
+impl fmt::Display for DigestKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for DigestKind {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "sha-256" => Ok(DigestKind::Sha256),
+            "xxh3-64" => Ok(DigestKind::Xxh3_64),
+            "xxh3-128" => Ok(DigestKind::Xxh3_128),
+            _ => Err(anyhow!("Unrecognised digest kind: {s}")),
+        }
+    }
+}
+
+/// Digest `data` using `kind`.
+pub fn digest(kind: DigestKind, data: &[u8]) -> Vec<u8> {
+    match kind {
+        DigestKind::Sha256 => sha2::Sha256::digest(data).to_vec(),
+        DigestKind::Xxh3_64 => xxhash_rust::xxh3::xxh3_64(data).to_be_bytes().to_vec(),
+        DigestKind::Xxh3_128 => xxhash_rust::xxh3::xxh3_128(data).to_be_bytes().to_vec(),
+    }
+}
@@ -1,5 +1,6 @@
 pub mod chunkers;
 mod controlfile;
+pub mod digest;
 pub mod rollsum;
 mod search;
 
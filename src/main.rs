@@ -4,7 +4,13 @@ use kdam::{Bar, BarExt};
 use psync::*;
 use rangemap::RangeMap;
 use sha2::{Digest, Sha256};
-use std::{fs::File, io::Write, path::PathBuf};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::Write,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
 use tracing::*;
 
 #[derive(Parser)]
@@ -23,6 +29,33 @@ enum Cmd {
         /// Treat input as a tarball and chunk on entry boundaries
         #[clap(long, short)]
         tar: bool,
+        /// Treat input as an Android sparse image and chunk on raw-chunk boundaries
+        #[clap(long)]
+        sparse: bool,
+        /// Use content-defined chunking (FastCDC) instead of uniform chunks
+        #[clap(long)]
+        cdc: bool,
+        /// Minimum chunk size for --cdc
+        #[clap(long, default_value = "2048")]
+        cdc_min: usize,
+        /// Average chunk size for --cdc
+        #[clap(long, default_value = "8192")]
+        cdc_avg: usize,
+        /// Maximum chunk size for --cdc
+        #[clap(long, default_value = "65536")]
+        cdc_max: usize,
+        /// Use the Asymmetric Extremum chunker instead of uniform chunks
+        #[clap(long)]
+        ae: bool,
+        /// Target chunk size for --ae
+        #[clap(long, default_value = "8192")]
+        ae_size: usize,
+        /// Compare chunkers on this file instead of writing a control file
+        #[clap(long)]
+        benchmark: bool,
+        /// Digest algorithm used for chunk hashes: sha-256, xxh3-64, or xxh3-128
+        #[clap(long, default_value = "sha-256")]
+        hash: String,
     },
 }
 
@@ -41,13 +74,43 @@ fn main() -> anyhow::Result<()> {
             path,
             max_size,
             tar,
-        } => chunk(path, max_size, tar),
+            sparse,
+            cdc,
+            cdc_min,
+            cdc_avg,
+            cdc_max,
+            ae,
+            ae_size,
+            benchmark,
+            hash,
+        } => chunk(
+            path, max_size, tar, sparse, cdc, cdc_min, cdc_avg, cdc_max, ae, ae_size, benchmark,
+            hash,
+        ),
     }
 }
 
-fn chunk(path: PathBuf, max_size: usize, tar: bool) -> anyhow::Result<()> {
+#[allow(clippy::too_many_arguments)]
+fn chunk(
+    path: PathBuf,
+    max_size: usize,
+    tar: bool,
+    sparse: bool,
+    cdc: bool,
+    cdc_min: usize,
+    cdc_avg: usize,
+    cdc_max: usize,
+    ae: bool,
+    ae_size: usize,
+    benchmark: bool,
+    hash: String,
+) -> anyhow::Result<()> {
     let file = File::open(&path)?;
     let mmap = unsafe { memmap2::Mmap::map(&file)? };
+    if benchmark {
+        return run_benchmark(&mmap[..]);
+    }
+    let digest_kind: digest::DigestKind = hash.parse()?;
     let outpath = format!("{}.psync", path.display());
     let mut outfile = match File::options().write(true).create_new(true).open(&outpath) {
         Ok(x) => x,
@@ -62,11 +125,16 @@ fn chunk(path: PathBuf, max_size: usize, tar: bool) -> anyhow::Result<()> {
     writeln!(outfile, "# This file was created by psync")?;
     writeln!(outfile, "# The length of the source file, in bytes")?;
     writeln!(outfile, "Length: {}", mmap.len())?;
-    writeln!(outfile, "# The SHA-256 of the entire source file")?;
     writeln!(
         outfile,
-        "SHA-256: {}",
-        hex::encode(sha2::Sha256::digest(&mmap[..]))
+        "# The digest algorithm used for chunk hashes and the line below"
+    )?;
+    writeln!(outfile, "Hash: {digest_kind}")?;
+    writeln!(outfile, "# The digest of the entire source file")?;
+    writeln!(
+        outfile,
+        "Digest: {}",
+        hex::encode(digest::digest(digest_kind, &mmap[..]))
     )?;
     writeln!(
         outfile,
@@ -77,18 +145,33 @@ fn chunk(path: PathBuf, max_size: usize, tar: bool) -> anyhow::Result<()> {
         outfile,
         "# The rest of this file defines chunks within the source file"
     )?;
-    writeln!(outfile, "# from\tlength\tstart_mark\tsha-256")?;
+    writeln!(outfile, "# from\tlength\tstart_mark\tdigest")?;
     writeln!(outfile, "---")?;
     let mut pb = mk_bar(mmap.len())?;
     let mut breakpoints = vec![0, mmap.len()];
     if tar {
-        breakpoints.extend(chunkers::chunk_tarball(&mmap[..]));
+        breakpoints.extend(chunkers::appearance_breakpoints(chunkers::chunk_tarball(
+            &mmap[..],
+            digest_kind,
+        )));
+    }
+    if sparse {
+        breakpoints.extend(chunkers::appearance_breakpoints(chunkers::chunk_sparse(
+            &mmap[..],
+            digest_kind,
+        )?));
+    }
+    if cdc {
+        breakpoints.extend(chunkers::chunk_fastcdc(&mmap[..], cdc_min, cdc_avg, cdc_max));
+    }
+    if ae {
+        breakpoints.extend(chunkers::chunk_ae(&mmap[..], ae_size));
     }
     chunkers::split_large_chunks(&mut breakpoints, max_size);
     breakpoints.sort();
     breakpoints.dedup();
     for (from, to) in breakpoints.iter().zip(breakpoints.iter().skip(1)) {
-        let ap = Appearance::new(&mmap[..], *from, to - from);
+        let ap = Appearance::new(&mmap[..], *from, to - from, digest_kind);
         writeln!(outfile, "{ap}")?;
         pb.update_to(ap.from);
     }
@@ -98,6 +181,64 @@ fn chunk(path: PathBuf, max_size: usize, tar: bool) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Target chunk sizes to benchmark, in bytes.
+const BENCHMARK_SIZES: [usize; 4] = [4 * 1024, 8 * 1024, 16 * 1024, 64 * 1024];
+
+fn run_benchmark(file: &[u8]) -> anyhow::Result<()> {
+    println!(
+        "{:<10} {:>10} {:>12} {:>10} {:>10} {:>10}",
+        "chunker", "target", "avg size", "stddev", "savings", "MB/s"
+    );
+    for &size in &BENCHMARK_SIZES {
+        let start = Instant::now();
+        let breakpoints = uniform_breakpoints(file, size);
+        report_chunker("uniform", size, file, &breakpoints, start.elapsed());
+
+        let start = Instant::now();
+        let breakpoints = chunkers::chunk_fastcdc(file, size / 4, size, size * 8);
+        report_chunker("fastcdc", size, file, &breakpoints, start.elapsed());
+
+        let start = Instant::now();
+        let breakpoints = chunkers::chunk_ae(file, size);
+        report_chunker("ae", size, file, &breakpoints, start.elapsed());
+    }
+    Ok(())
+}
+
+fn uniform_breakpoints(file: &[u8], size: usize) -> Vec<usize> {
+    let mut breakpoints: Vec<usize> = (size..file.len()).step_by(size).collect();
+    breakpoints.push(file.len());
+    breakpoints
+}
+
+fn report_chunker(name: &str, target: usize, file: &[u8], breakpoints: &[usize], elapsed: Duration) {
+    let mut unique_bytes = HashMap::<[u8; 32], usize>::default();
+    let mut lengths = Vec::with_capacity(breakpoints.len());
+    let mut from = 0;
+    for &to in breakpoints {
+        let len = to - from;
+        let hash: [u8; 32] = Sha256::digest(&file[from..to]).into();
+        unique_bytes.entry(hash).or_insert(len);
+        lengths.push(len);
+        from = to;
+    }
+    let total_bytes: usize = lengths.iter().sum();
+    let n = lengths.len() as f64;
+    let avg = total_bytes as f64 / n;
+    let variance = lengths.iter().map(|&l| (l as f64 - avg).powi(2)).sum::<f64>() / n;
+    let savings = 1.0 - (unique_bytes.values().sum::<usize>() as f64 / total_bytes as f64);
+    let mb_s = (file.len() as f64 / (1024.0 * 1024.0)) / elapsed.as_secs_f64();
+    println!(
+        "{:<10} {:>10} {:>12.0} {:>10.0} {:>9.1}% {:>10.1}",
+        name,
+        target,
+        avg,
+        variance.sqrt(),
+        savings * 100.0,
+        mb_s,
+    );
+}
+
 fn mk_bar(total: usize) -> anyhow::Result<Bar> {
     Bar::builder()
         .total(total)
@@ -114,8 +255,8 @@ fn search(control_file: PathBuf, seed: PathBuf) -> anyhow::Result<()> {
     let mmap = unsafe { memmap2::Mmap::map(&file)? };
 
     if mmap.len() == control_file.total_len {
-        let our_hash = Sha256::digest(&mmap[..]);
-        if our_hash[..] == control_file.total_sha256[..] {
+        let our_digest = digest::digest(control_file.digest_kind, &mmap[..]);
+        if our_digest == control_file.total_digest {
             info!("File is up-to-date");
             return Ok(());
         }
@@ -170,3 +311,22 @@ fn search(control_file: PathBuf, seed: PathBuf) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uniform_breakpoints_covers_file_in_equal_chunks() {
+        let file = vec![0u8; 10_000];
+        let breakpoints = uniform_breakpoints(&file, 4_000);
+        assert_eq!(breakpoints, vec![4_000, 8_000, 10_000]);
+    }
+
+    #[test]
+    fn uniform_breakpoints_handles_file_shorter_than_one_chunk() {
+        let file = vec![0u8; 100];
+        let breakpoints = uniform_breakpoints(&file, 4_000);
+        assert_eq!(breakpoints, vec![100]);
+    }
+}